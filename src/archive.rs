@@ -0,0 +1,189 @@
+use image::RgbaImage;
+
+use crate::metadata::{self, Provenance};
+
+/// How descrambled pages should be packaged on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One numbered image file per page in a plain directory (the original behavior).
+    Dir,
+    /// A single `.cbz` archive, which is a ZIP of the page images in reading order.
+    Cbz,
+    /// A single `.epub` with one XHTML page per image and a generated nav/spine.
+    Epub,
+}
+
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Dir => "",
+            OutputFormat::Cbz => "cbz",
+            OutputFormat::Epub => "epub",
+        }
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.extension() {
+            "" => write!(f, "dir"),
+            ext => write!(f, "{}", ext),
+        }
+    }
+}
+
+/// Image encoding used for loose (`Dir`) output. Archive formats always
+/// embed PNGs internally regardless of this setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageExt {
+    Png,
+    Jpg,
+}
+
+impl ImageExt {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ImageExt::Png => "png",
+            ImageExt::Jpg => "jpg",
+        }
+    }
+}
+
+/// A descrambled page plus the dimensions of the scrambled tile sheet it
+/// came from, carried along so provenance metadata can reference them.
+pub struct Page {
+    pub image: RgbaImage,
+    pub scrambled_width: u32,
+    pub scrambled_height: u32,
+}
+
+/// Everything needed to stamp provenance metadata onto a saved page.
+pub struct MetadataContext<'a> {
+    pub source_url: &'a str,
+    pub id: u32,
+    pub no_metadata: bool,
+}
+
+impl<'a> MetadataContext<'a> {
+    fn provenance(&self, page: &Page, page_index: u32) -> Option<Provenance<'_>> {
+        if self.no_metadata {
+            return None;
+        }
+        Some(Provenance {
+            source_url: self.source_url,
+            id: self.id,
+            page_index,
+            scrambled_width: page.scrambled_width,
+            scrambled_height: page.scrambled_height,
+        })
+    }
+}
+
+fn encode_page(page: &Page, page_index: u32, ext: ImageExt, meta: &MetadataContext) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let provenance = meta.provenance(page, page_index);
+    match ext {
+        ImageExt::Png => metadata::png_bytes(&page.image, provenance.as_ref()),
+        ImageExt::Jpg => metadata::jpeg_bytes(&page.image, 90, provenance.as_ref()),
+    }
+}
+
+/// Directory a comic's loose page files (and its resume manifest) live in.
+pub fn comic_dir(output_dir: &str, id: u32) -> String {
+    format!("{}/{}", output_dir.trim_end_matches('/'), id)
+}
+
+/// Path a single page would be written to in `Dir` mode, without writing it.
+pub fn dir_page_path(output_dir: &str, id: u32, page_index: u32, ext: ImageExt) -> String {
+    format!("{}/{}.{}", comic_dir(output_dir, id), page_index, ext.as_str())
+}
+
+/// Writes a single descrambled page straight into the comic's directory,
+/// letting callers save pages incrementally as they're fetched rather than
+/// buffering the whole comic before writing anything out. Returns the MD5
+/// of the bytes written, so the caller can record it in the resume manifest.
+pub fn save_page_to_dir(output_dir: &str, id: u32, page_index: u32, page: &Page, ext: ImageExt, meta: &MetadataContext) -> Result<String, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(comic_dir(output_dir, id))?;
+    let bytes = encode_page(page, page_index, ext, meta)?;
+    std::fs::write(dir_page_path(output_dir, id, page_index, ext), &bytes)?;
+    Ok(crate::manifest::hash(&bytes))
+}
+
+fn write_dir(output_dir: &str, id: u32, first_page: u32, pages: &[Page], ext: ImageExt, meta: &MetadataContext) -> Result<(), Box<dyn std::error::Error>> {
+    for (i, page) in pages.iter().enumerate() {
+        save_page_to_dir(output_dir, id, first_page + i as u32, page, ext, meta)?;
+    }
+    Ok(())
+}
+
+fn write_cbz(output_dir: &str, id: u32, first_page: u32, pages: &[Page], meta: &MetadataContext) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    let path = format!("{}/{}.cbz", output_dir.trim_end_matches('/'), id);
+    let file = std::fs::File::create(&path)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored);
+    for (i, page) in pages.iter().enumerate() {
+        let page_num = first_page + i as u32;
+        // Zero-padded so lexical order matches page order in any CBZ reader.
+        zip.start_file(format!("{:03}.png", page_num), options)?;
+        let bytes = encode_page(page, page_num, ImageExt::Png, meta)?;
+        std::io::Write::write_all(&mut zip, &bytes)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn write_epub(output_dir: &str, id: u32, first_page: u32, pages: &[Page], meta: &MetadataContext) -> Result<(), Box<dyn std::error::Error>> {
+    use epub_builder::{EpubBuilder, EpubContent, ZipLibrary};
+
+    std::fs::create_dir_all(output_dir)?;
+    let path = format!("{}/{}.epub", output_dir.trim_end_matches('/'), id);
+    let mut builder = EpubBuilder::new(ZipLibrary::new()?)?;
+    builder.metadata("title", format!("{}", id))?;
+
+    for (i, page) in pages.iter().enumerate() {
+        let page_num = first_page + i as u32;
+        let image_name = format!("images/{:03}.png", page_num);
+        let bytes = encode_page(page, page_num, ImageExt::Png, meta)?;
+        builder.add_resource(&image_name, bytes.as_slice(), "image/png")?;
+
+        let xhtml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <html xmlns=\"http://www.w3.org/1999/xhtml\">\n\
+             <body><img src=\"{}\"/></body></html>",
+            image_name
+        );
+        let chapter_name = format!("page_{:03}.xhtml", page_num);
+        builder.add_content(
+            EpubContent::new(chapter_name, xhtml.as_bytes())
+                .title(format!("Page {}", page_num)),
+        )?;
+    }
+
+    let mut out = std::fs::File::create(&path)?;
+    builder.generate(&mut out)?;
+    Ok(())
+}
+
+/// Packages the descrambled `pages` into the requested `format` under
+/// `output_dir`, named after the comic's numeric `id`. `first_page` is the
+/// page number of `pages[0]` (1 unless `--pages` started elsewhere), so
+/// archives and provenance stay numbered after the true source page rather
+/// than the slice position. `ext` selects the image encoding for loose
+/// (`Dir`) output; archive formats always embed PNGs. `meta` controls the
+/// provenance metadata stamped onto each page.
+pub fn write_output(
+    format: OutputFormat,
+    output_dir: &str,
+    id: u32,
+    first_page: u32,
+    pages: &[Page],
+    ext: ImageExt,
+    meta: &MetadataContext,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Dir => write_dir(output_dir, id, first_page, pages, ext, meta),
+        OutputFormat::Cbz => write_cbz(output_dir, id, first_page, pages, meta),
+        OutputFormat::Epub => write_epub(output_dir, id, first_page, pages, meta),
+    }
+}
@@ -0,0 +1,116 @@
+use clap::{Parser, ValueEnum};
+
+use crate::archive::{ImageExt, OutputFormat};
+
+/// Validates that `s` looks like a kirapo.jp viewer URL, so a malformed URL
+/// is rejected as a typed argument error instead of a manual process exit.
+fn parse_url(s: &str) -> Result<String, String> {
+    let re = regex::Regex::new(r"https://kirapo\.jp/.*/viewer$").unwrap();
+    if re.is_match(s) {
+        Ok(s.to_string())
+    } else {
+        Err(format!("not a kirapo.jp viewer url: {}", s))
+    }
+}
+
+/// Validates that `--jobs` is at least 1, so `0` can't silently make the
+/// download stream never poll its inner futures and hang forever.
+fn parse_jobs(s: &str) -> Result<usize, String> {
+    let jobs: usize = s.parse().map_err(|_| format!("not a number: {}", s))?;
+    if jobs == 0 {
+        return Err("jobs must be at least 1".to_string());
+    }
+    Ok(jobs)
+}
+
+/// An inclusive range of 1-based page indices, e.g. `3-7`.
+#[derive(Debug, Clone, Copy)]
+pub struct PageRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl std::str::FromStr for PageRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("expected a range like 3-7, got: {}", s))?;
+        let start: u32 = start.parse().map_err(|_| format!("invalid range start: {}", start))?;
+        let end: u32 = end.parse().map_err(|_| format!("invalid range end: {}", end))?;
+        if start == 0 || end < start {
+            return Err(format!("invalid page range: {}-{}", start, end));
+        }
+        Ok(PageRange { start, end })
+    }
+}
+
+/// The `--format` values a user can pick; `Jpg`/`Png` select the loose
+/// directory layout with the given image encoding, `Cbz`/`Epub` select the
+/// corresponding archive (always PNG-encoded internally).
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Format {
+    Dir,
+    Cbz,
+    Epub,
+    Jpg,
+    Png,
+}
+
+impl Format {
+    /// Splits the flat CLI format into the archive container and the image
+    /// encoding used for loose-file output.
+    pub fn to_output(self) -> (OutputFormat, ImageExt) {
+        match self {
+            Format::Dir => (OutputFormat::Dir, ImageExt::Png),
+            Format::Cbz => (OutputFormat::Cbz, ImageExt::Png),
+            Format::Epub => (OutputFormat::Epub, ImageExt::Png),
+            Format::Jpg => (OutputFormat::Dir, ImageExt::Jpg),
+            Format::Png => (OutputFormat::Dir, ImageExt::Png),
+        }
+    }
+}
+
+/// Downloads and descrambles a kirapo.jp comic.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// URL of the comic to download, e.g. https://kirapo.jp/*/viewer
+    #[arg(value_parser = parse_url)]
+    pub url: String,
+
+    /// Directory the output is written into.
+    #[arg(long, default_value = "./")]
+    pub output: String,
+
+    /// Output format: dir, cbz, epub, jpg or png.
+    #[arg(long, value_enum, default_value = "dir")]
+    pub format: Format,
+
+    /// Number of pages fetched concurrently.
+    #[arg(long, default_value_t = 8, value_parser = parse_jobs)]
+    pub jobs: usize,
+
+    /// Inclusive page range to fetch, e.g. 3-7. Skips page-count discovery.
+    #[arg(long)]
+    pub pages: Option<PageRange>,
+
+    /// Which entry of the ptimg `views` array to render, 0-based. Most
+    /// comics only have one view; pick a different one for pages that ship
+    /// alternate (e.g. rotated) renditions.
+    #[arg(long, default_value_t = 0)]
+    pub view: usize,
+
+    /// Don't embed the source URL/id/page/scrambled-size provenance metadata.
+    #[arg(long)]
+    pub no_metadata: bool,
+
+    /// Suppress progress bars, for non-TTY/scripted use.
+    #[arg(long)]
+    pub quiet: bool,
+
+    /// Log every fetched URL.
+    #[arg(long)]
+    pub verbose: bool,
+}
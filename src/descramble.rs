@@ -0,0 +1,232 @@
+use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, RgbaImage};
+use serde_json::Value;
+
+/// A source crop rectangle within the scrambled tile sheet.
+#[derive(Debug, Clone, Copy)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+/// How a cropped tile is reoriented before being composited onto the canvas.
+#[derive(Debug, Clone, Copy)]
+pub enum Transform {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+}
+
+impl Transform {
+    fn parse(s: &str) -> Result<Transform, Box<dyn std::error::Error>> {
+        match s {
+            "r90" => Ok(Transform::Rotate90),
+            "r180" => Ok(Transform::Rotate180),
+            "r270" => Ok(Transform::Rotate270),
+            "fh" => Ok(Transform::FlipHorizontal),
+            "fv" => Ok(Transform::FlipVertical),
+            other => Err(format!("Unknown tile transform: {}", other).into()),
+        }
+    }
+
+    fn apply(&self, tile: RgbaImage) -> RgbaImage {
+        match self {
+            Transform::Identity => tile,
+            Transform::Rotate90 => image::imageops::rotate90(&tile),
+            Transform::Rotate180 => image::imageops::rotate180(&tile),
+            Transform::Rotate270 => image::imageops::rotate270(&tile),
+            Transform::FlipHorizontal => image::imageops::flip_horizontal(&tile),
+            Transform::FlipVertical => image::imageops::flip_vertical(&tile),
+        }
+    }
+}
+
+/// One tile: a crop of the scrambled source, an optional transform, and
+/// where it lands on the descrambled canvas.
+#[derive(Debug)]
+pub struct Tile {
+    pub src: Rect,
+    pub dst: (u32, u32),
+    pub transform: Transform,
+}
+
+/// One entry of the ptimg `views` array: the descrambled canvas size and
+/// the tiles that compose it.
+#[derive(Debug)]
+pub struct View {
+    pub width: u32,
+    pub height: u32,
+    pub tiles: Vec<Tile>,
+}
+
+/// Parses every entry of the ptimg `views` array (not just the first), each
+/// `coords` entry looking like `i:SRC_X,SRC_Y+W,H>DST_X,DST_Y` with an
+/// optional `:TRANSFORM` suffix (`r90`, `r180`, `r270`, `fh`, `fv`).
+pub fn parse_json(json_str: &str) -> Result<Vec<View>, Box<dyn std::error::Error>> {
+    let value: Value = serde_json::from_str(json_str)?;
+    let views_json = value["views"].as_array().ok_or("No views field in the json")?;
+
+    let mut views = Vec::new();
+    for view_json in views_json {
+        let view_json = view_json.as_object().ok_or("No views field in the json")?;
+        let width = view_json["width"]
+            .as_u64()
+            .ok_or("Failed to parse width from json")? as u32;
+        let height = view_json["height"]
+            .as_u64()
+            .ok_or("Failed to parse height from json")? as u32;
+        let coords_value = view_json["coords"]
+            .as_array()
+            .ok_or("Failed to parse coords from json")?;
+
+        let mut tiles: Vec<Tile> = Vec::new();
+        for coords_str in coords_value {
+            let s = coords_str
+                .as_str()
+                .ok_or("Failed to convert a coords entry into a String")?
+                .strip_prefix("i:")
+                .ok_or("Failed to strip the 'i:' prefix")?;
+            let (src_part, dst_part) = s
+                .split_once('>')
+                .ok_or("Failed to split the src and dst parts")?;
+            let (src_pos, size) = src_part
+                .split_once('+')
+                .ok_or("Failed to split the src and size parts")?;
+            let (dst_part, transform) = match dst_part.split_once(':') {
+                Some((dst_part, transform)) => (dst_part, Transform::parse(transform)?),
+                None => (dst_part, Transform::Identity),
+            };
+
+            let src_pos: Vec<&str> = src_pos.split(',').collect();
+            let size: Vec<&str> = size.split(',').collect();
+            let dst_pos: Vec<&str> = dst_part.split(',').collect();
+
+            let src_x = src_pos[0].parse::<u32>()?;
+            let src_y = src_pos[1].parse::<u32>()?;
+            let w = size[0].parse::<u32>()?;
+            let h = size[1].parse::<u32>()?;
+            let dst_x = dst_pos[0].parse::<u32>()?;
+            let dst_y = dst_pos[1].parse::<u32>()?;
+
+            tiles.push(Tile {
+                src: Rect { x: src_x, y: src_y, w, h },
+                dst: (dst_x, dst_y),
+                transform,
+            });
+        }
+
+        views.push(View { width, height, tiles });
+    }
+
+    Ok(views)
+}
+
+/// Assembles one descrambled canvas per entry of `views`, each sized and
+/// tiled independently (each `View` is a complete, self-contained rendition
+/// of the page, not a layer to be merged with the others). Returns a
+/// descriptive error instead of panicking if a tile's source or
+/// destination rect falls outside its image's bounds.
+pub fn descramble(img: &DynamicImage, views: &[View]) -> Result<Vec<RgbaImage>, Box<dyn std::error::Error>> {
+    if views.is_empty() {
+        return Err("No views to descramble".into());
+    }
+    let (src_w, src_h) = img.dimensions();
+
+    views
+        .iter()
+        .map(|view| {
+            let (canvas_w, canvas_h) = (view.width, view.height);
+            let mut canvas: RgbaImage = ImageBuffer::new(canvas_w, canvas_h);
+            for tile in &view.tiles {
+                if tile.src.x + tile.src.w > src_w || tile.src.y + tile.src.h > src_h {
+                    return Err(format!(
+                        "Tile source rect ({}, {}, {}x{}) is out of bounds for a {}x{} source image",
+                        tile.src.x, tile.src.y, tile.src.w, tile.src.h, src_w, src_h
+                    )
+                    .into());
+                }
+                let cropped = img.crop_imm(tile.src.x, tile.src.y, tile.src.w, tile.src.h).to_rgba8();
+                let transformed = tile.transform.apply(cropped);
+                if tile.dst.0 + transformed.width() > canvas_w || tile.dst.1 + transformed.height() > canvas_h {
+                    return Err(format!(
+                        "Tile destination ({}, {}) with size {}x{} is out of bounds for a {}x{} canvas",
+                        tile.dst.0, tile.dst.1, transformed.width(), transformed.height(), canvas_w, canvas_h
+                    )
+                    .into());
+                }
+                canvas.copy_from(&transformed, tile.dst.0, tile.dst.1)?;
+            }
+            Ok(canvas)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn parse_json_handles_multiple_views_and_transforms() {
+        let json = r#"{
+            "views": [
+                {"width": 2, "height": 1, "coords": ["i:0,0+1,1>0,0", "i:1,0+1,1>1,0:r180"]},
+                {"width": 1, "height": 1, "coords": ["i:0,0+1,1>0,0"]}
+            ]
+        }"#;
+        let views = parse_json(json).unwrap();
+        assert_eq!(views.len(), 2);
+        assert_eq!((views[0].width, views[0].height), (2, 1));
+        assert_eq!(views[0].tiles.len(), 2);
+        assert!(matches!(views[0].tiles[1].transform, Transform::Rotate180));
+        assert_eq!((views[1].width, views[1].height), (1, 1));
+        assert_eq!(views[1].tiles.len(), 1);
+    }
+
+    #[test]
+    fn descramble_produces_one_canvas_per_view_with_its_own_size() {
+        let mut src: RgbaImage = ImageBuffer::new(2, 1);
+        src.put_pixel(0, 0, Rgba([1, 2, 3, 255]));
+        src.put_pixel(1, 0, Rgba([4, 5, 6, 255]));
+        let img = DynamicImage::ImageRgba8(src);
+
+        let views = vec![
+            View {
+                width: 2,
+                height: 1,
+                tiles: vec![
+                    Tile { src: Rect { x: 0, y: 0, w: 1, h: 1 }, dst: (0, 0), transform: Transform::Identity },
+                    Tile { src: Rect { x: 1, y: 0, w: 1, h: 1 }, dst: (1, 0), transform: Transform::Identity },
+                ],
+            },
+            View {
+                width: 1,
+                height: 1,
+                tiles: vec![Tile { src: Rect { x: 1, y: 0, w: 1, h: 1 }, dst: (0, 0), transform: Transform::Identity }],
+            },
+        ];
+
+        let canvases = descramble(&img, &views).unwrap();
+        assert_eq!(canvases.len(), 2);
+        assert_eq!(canvases[0].dimensions(), (2, 1));
+        assert_eq!(*canvases[0].get_pixel(0, 0), Rgba([1, 2, 3, 255]));
+        assert_eq!(*canvases[0].get_pixel(1, 0), Rgba([4, 5, 6, 255]));
+        assert_eq!(canvases[1].dimensions(), (1, 1));
+        assert_eq!(*canvases[1].get_pixel(0, 0), Rgba([4, 5, 6, 255]));
+    }
+
+    #[test]
+    fn descramble_rejects_out_of_bounds_tile() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::new(2, 1));
+        let views = vec![View {
+            width: 1,
+            height: 1,
+            tiles: vec![Tile { src: Rect { x: 0, y: 0, w: 2, h: 1 }, dst: (0, 0), transform: Transform::Identity }],
+        }];
+        assert!(descramble(&img, &views).is_err());
+    }
+}
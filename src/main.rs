@@ -1,175 +1,258 @@
-use std::io::Write;
-use serde_json::Value;
-use image::GenericImage;
-use image::{DynamicImage, ImageBuffer, RgbaImage};
-
-type CoordsTuple = (u32, u32, u32, u32, u32, u32);
-#[derive(Debug)]
-struct Views {
-    width: u32,
-    height: u32,
-    coords: Vec<CoordsTuple>,
-}
+mod archive;
+mod cli;
+mod descramble;
+mod manifest;
+mod metadata;
+mod reporter;
 
-impl std::fmt::Display for Views {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Views {{ width: {}, height: {}, coords: {:?} }}", self.width, self.height, self.coords)
-    }
-}
+use std::time::Duration;
+use clap::Parser;
+use futures::stream::{self, StreamExt};
+use image::DynamicImage;
+use reqwest::Client;
 
-fn parse_json(json_str: &str) -> Result<Views, Box<dyn std::error::Error>> {
-    let value: Value = serde_json::from_str(&json_str)?;
-    
-    // Get the views
-    let views_json = value["views"]
-        .get(0)
-        .ok_or("No views field in the json")?
-        .as_object()
-        .ok_or("No views field in the json")?;
-    let width = views_json["width"]
-        .as_u64()
-        .ok_or("Failed to parse width from json")?
-        as u32;
-    let height = views_json["height"]
-        .as_u64()
-        .ok_or("Failed to parse height from json")?
-        as u32;
-    let coords_value = views_json["coords"]
-        .as_array()
-        .ok_or("Failed to parse coords from json")?;
-    
-    let mut coords: Vec<CoordsTuple> = Vec::new();
-    for coords_str in coords_value {
-        let s = coords_str
-            .as_str()
-            .ok_or("Failed to convert a coords entry into a String")?
-            .strip_prefix("i:")
-            .ok_or("Failed to strip the 'i:' prefix")?;
-        let (src_part, dst_part) = s
-            .split_once('>')
-            .ok_or("Failed to split the src and dst parts")?;
-        let (src_pos, size) = src_part
-            .split_once('+')
-            .ok_or("Failed to split the src and size parts")?;
-
-        let src_pos: Vec<&str> = src_pos.split(',').collect();
-        let size: Vec<&str> = size.split(',').collect();
-        let dst_pos: Vec<&str> = dst_part.split(',').collect();
-
-        let src_x = src_pos[0].parse::<u32>()?;
-        let src_y = src_pos[1].parse::<u32>()?;
-        let w = size[0].parse::<u32>()?;
-        let h = size[1].parse::<u32>()?;
-        let dst_x = dst_pos[0].parse::<u32>()?;
-        let dst_y = dst_pos[1].parse::<u32>()?;
-        coords.push((src_x, src_y, w, h, dst_x, dst_y));
-    }
+use archive::{MetadataContext, OutputFormat, Page};
+use cli::Cli;
+use descramble::View;
+use manifest::Manifest;
+use reporter::Reporter;
+
+/// Number of times a transient (5xx / connection) error is retried per request.
+const MAX_RETRIES: u32 = 3;
 
-    Ok(Views {
-        width,
-        height,
-        coords,
-    })
+/// Sends a GET request to `url`, retrying up to `MAX_RETRIES` times with a
+/// linear backoff on connection errors or 5xx responses.
+async fn get_with_retry(client: &Client, url: &str, reporter: &Reporter) -> Result<reqwest::Response, reqwest::Error> {
+    reporter.log_url(url);
+    let mut attempt = 0;
+    loop {
+        let result = client.get(url).send().await;
+        let transient = match &result {
+            Ok(resp) => resp.status().is_server_error(),
+            Err(_) => true,
+        };
+        if !transient || attempt >= MAX_RETRIES {
+            return result;
+        }
+        attempt += 1;
+        tokio::time::sleep(Duration::from_millis(500 * attempt as u64)).await;
+    }
 }
 
-fn descramble(img: &DynamicImage, views: &Views) -> RgbaImage {
-    let mut orig = ImageBuffer::new(views.width, views.height);
-    for (src_x, src_y, w, h, dst_x, dst_y) in views.coords.iter() {
-        let tile = img.crop_imm(*src_x, *src_y, *w, *h);
-        orig.copy_from(&tile, *dst_x, *dst_y);  // TODO use GenericImageView::view
+/// Checks whether page `i` exists by probing its image, returning `Ok(true)`
+/// on 200 and `Ok(false)` on 404.
+async fn page_exists(client: &Client, url: &str, i: u32, reporter: &Reporter) -> Result<bool, Box<dyn std::error::Error>> {
+    let img_url = format!("{}{:04}.jpg", url, i);
+    let resp = get_with_retry(client, &img_url, reporter).await?;
+    match resp.status().as_u16() {
+        200 => Ok(true),
+        404 => Ok(false),
+        status => Err(format!("Error while probing page {}: {}", i, status).into()),
     }
-    orig
 }
 
-fn main() {
-    // Parse args or print help
-    fn print_usage(args: Vec<String>) {
-        println!("Usage: {} https://kirapo.jp/*/viewer\n\tThe argument is the url of the comic you need to download. Ends with /viewer", args[0]);
+/// Discovers the number of pages by doubling the probed index (1, 2, 4, 8, ...)
+/// until a 404 is found, then binary-searching the 200/404 boundary.
+async fn discover_page_count(client: &Client, url: &str, reporter: &Reporter) -> Result<u32, Box<dyn std::error::Error>> {
+    let mut lo = 0u32; // last page known to exist (0 = none yet)
+    let mut hi = 1u32; // first page probed; doubled until it's missing
+    while page_exists(client, url, hi, reporter).await? {
+        lo = hi;
+        hi *= 2;
     }
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 || args[1] == "--help" || args[1] == "-h" {
-        print_usage(args);
-        std::process::exit(1);
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if page_exists(client, url, mid, reporter).await? {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
     }
-    let re = regex::Regex::new(r"https://kirapo\.jp/.*/viewer$").unwrap();
-    if !re.is_match(&args[1]) {
-        println!("Invalid url: {}", args[1]);
-        print_usage(args);
-        std::process::exit(1);
+    Ok(lo)
+}
+
+/// Downloads and parses the image and ptimg manifest for a single page. A
+/// source JPEG that fails to decode (a truncated/corrupted download) is
+/// retried rather than left to panic in `descramble`.
+async fn fetch_page(client: &Client, url: &str, i: u32, reporter: &Reporter) -> Result<(DynamicImage, Vec<View>), Box<dyn std::error::Error>> {
+    let img_url = format!("{}{:04}.jpg", url, i);
+    let img = {
+        let mut attempt = 0;
+        loop {
+            let img_resp = get_with_retry(client, &img_url, reporter).await?;
+            if !img_resp.status().is_success() {
+                return Err(format!("Error while downloading image {}: {}", i, img_resp.status()).into());
+            }
+            let buffer = img_resp.bytes().await?;
+            match image::load_from_memory(&buffer) {
+                Ok(img) => break img,
+                Err(_) if attempt < MAX_RETRIES => {
+                    attempt += 1;
+                    tokio::time::sleep(Duration::from_millis(500 * attempt as u64)).await;
+                    continue;
+                }
+                Err(err) => return Err(format!("Corrupted image for page {}: {}", i, err).into()),
+            }
+        }
+    };
+
+    let json_url = format!("{}{:04}.ptimg.json", url, i);
+    let json_resp = get_with_retry(client, &json_url, reporter).await?;
+    if !json_resp.status().is_success() {
+        return Err(format!("Error while downloading manifest {}: {}", i, json_resp.status()).into());
     }
-    let url = format!("{}/data/", args[1].strip_suffix("/viewer").unwrap()).to_string();
-    let id = args[1]
-        .strip_suffix("/viewer")
-        .unwrap()
-        .rfind('/')
-        .unwrap();
-    let p = args[1].rfind('/').unwrap();
-    let id: u32 = args[1][id+1..p].parse().unwrap();
+    let json_str = json_resp.text().await?;
+    let views = descramble::parse_json(&json_str)?;
+
+    Ok((img, views))
+}
+
+/// Extracts the numeric comic id from a `.../<id>/viewer` URL.
+fn comic_id(viewer_url: &str) -> u32 {
+    let base = viewer_url.strip_suffix("/viewer").unwrap();
+    let id_start = base.rfind('/').unwrap() + 1;
+    base[id_start..].parse().unwrap()
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let (output_format, image_ext) = cli.format.to_output();
+    let reporter = Reporter::new(cli.quiet, cli.verbose);
 
-    // Download the images
-    let client = reqwest::blocking::Client::builder()
+    let data_url = format!("{}/data/", cli.url.strip_suffix("/viewer").unwrap());
+    let id = comic_id(&cli.url);
+
+    let client = Client::builder()
         .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/142.0.0.0 Safari/537.36")
         .build()
         .unwrap();
-    let mut imgs: Vec<image::DynamicImage> = Vec::new();
-    let mut views: Vec<Views> = Vec::new();
-    for i in 1.. {
-        print!("\rDownloading image {:04}...", i);
-        std::io::stdout().flush().unwrap();
-        
-        // Download the image
-        let img_url = format!("{}{:04}.jpg", url, i);
-        let img = client.get(&img_url).send();
-        if img.is_err() {
-            eprintln!("Error while downloading an image {}: {}", i, img.as_ref().unwrap().status());
-            std::process::exit(1);
-        }
-        let img = img.unwrap();
-        if img.status() == 404 {
-            break;
+
+    // Either take the explicit --pages range or discover the page count by
+    // probing (phase one).
+    let (first, last) = match cli.pages {
+        Some(range) => (range.start, range.end),
+        None => {
+            let spinner = reporter.spinner("Discovering page count...");
+            let page_count = discover_page_count(&client, &data_url, &reporter).await.unwrap_or_else(|err| {
+                reporter.error(&format!("while discovering page count: {}", err));
+                std::process::exit(1);
+            });
+            if let Some(spinner) = spinner {
+                spinner.finish_with_message(format!("{} pages found.", page_count));
+            }
+            (1, page_count)
         }
-        if img.status() != 200 {
-            eprintln!("Error while downloading an image {}: {}", i, img.status());
+    };
+    if last < first {
+        reporter.error("no pages found");
+        std::process::exit(1);
+    }
+    let page_count = last - first + 1;
+
+    // Dir output is incremental (one file per page) so it's the only format
+    // that can resume: a page is skipped if it's both on disk and recorded
+    // in the manifest from a prior run.
+    let resumable = output_format == OutputFormat::Dir;
+    let comic_dir = archive::comic_dir(&cli.output, id);
+    let manifest_path = Manifest::path_for(&comic_dir);
+    let mut manifest = if resumable { Manifest::load(&manifest_path) } else { Manifest::default() };
+
+    let to_fetch: Vec<u32> = (first..=last)
+        .filter(|&i| {
+            if !resumable {
+                return true;
+            }
+            let path = archive::dir_page_path(&cli.output, id, i, image_ext);
+            if manifest.verify(i, std::path::Path::new(&path)) {
+                return false;
+            }
+            if std::path::Path::new(&path).exists() {
+                reporter.warning(&format!("cached page {} is missing or corrupted, re-fetching", i));
+            }
+            true
+        })
+        .collect();
+    let skipped = page_count as usize - to_fetch.len();
+    if skipped > 0 {
+        reporter.success(&format!("Skipping {} already-downloaded pages...", skipped));
+    }
+
+    let meta_ctx = MetadataContext {
+        source_url: &cli.url,
+        id,
+        no_metadata: cli.no_metadata,
+    };
+
+    // Phase two: fetch every (image, manifest) pair through a bounded pool of
+    // concurrent tasks. In Dir mode each page is descrambled and saved as
+    // soon as it arrives; otherwise results are collected in order for a
+    // single archive write at the end.
+    let download_bar = reporter.bar("Downloading/descrambling", to_fetch.len() as u64);
+    let mut slots: Vec<Option<Page>> = (0..page_count).map(|_| None).collect();
+    let mut pending = stream::iter(to_fetch.clone())
+        .map(|i| {
+            let client = client.clone();
+            let data_url = data_url.clone();
+            let reporter = &reporter;
+            async move {
+                let res = fetch_page(&client, &data_url, i, reporter).await;
+                (i, res)
+            }
+        })
+        .buffer_unordered(cli.jobs);
+
+    while let Some((i, res)) = pending.next().await {
+        let (img, views) = res.unwrap_or_else(|err| {
+            reporter.error(&format!("while fetching page {}: {}", i, err));
             std::process::exit(1);
-        }
-        let buffer = img
-            .bytes()
-            .unwrap();
-        let img = image::load_from_memory(&buffer)
-            .unwrap();
-        imgs.push(img);
-
-        // Parse the json
-        let json_url = format!("{}{:04}.ptimg.json", url, i);
-        let json_resp = client.get(&json_url).send();
-        if json_resp.is_err() {
-            eprintln!("Error: {}", json_resp.as_ref().unwrap().status());
+        });
+        let (scrambled_width, scrambled_height) = image::GenericImageView::dimensions(&img);
+        let selected_view = std::slice::from_ref(views.get(cli.view).unwrap_or_else(|| {
+            reporter.error(&format!("page {} has no view {} (only {} view(s))", i, cli.view, views.len()));
             std::process::exit(1);
+        }));
+        let canvases = descramble::descramble(&img, selected_view).unwrap_or_else(|err| {
+            reporter.error(&format!("while descrambling page {}: {}", i, err));
+            std::process::exit(1);
+        });
+        let page = Page {
+            scrambled_width,
+            scrambled_height,
+            image: canvases.into_iter().next().unwrap(),
+        };
+        if resumable {
+            let hash = archive::save_page_to_dir(&cli.output, id, i, &page, image_ext, &meta_ctx).unwrap_or_else(|err| {
+                reporter.error(&format!("while saving page {}: {}", i, err));
+                std::process::exit(1);
+            });
+            manifest.record(i, hash);
+        }
+        slots[(i - first) as usize] = Some(page);
+        if let Some(bar) = &download_bar {
+            bar.inc(1);
         }
-        let json_str = json_resp.unwrap().text().unwrap();
-        let view = parse_json(&json_str).unwrap();
-        views.push(view);
     }
-    println!("\n{} images downloaded. Descrambling...", imgs.len());
-
-    // Descramble the images
-    let mut descrambled_imgs: Vec<image::RgbaImage> = Vec::new();
-    for (img, view) in imgs.iter().zip(views.iter()) {
-        let orig = descramble(img, view);
-        descrambled_imgs.push(orig);
+    if let Some(bar) = &download_bar {
+        bar.finish_with_message("Downloaded/descrambled all pages.");
     }
 
-    // Save the images
-    // make a directory
-    let dir = format!("./{}", id);
-    std::fs::create_dir(&dir).unwrap();
-    println!("Saving images into {}...", dir);
-    for (img, i) in descrambled_imgs.iter().zip(1..) {
-        print!("\rSaving image {:04} (of {:04})...", i, imgs.len());
-        std::io::stdout().flush().unwrap();
-        let path = format!("{}/{}.png", dir, i);
-        img.save(path).unwrap();
+    if resumable {
+        manifest.save(&manifest_path).unwrap_or_else(|err| {
+            reporter.error(&format!("while saving resume manifest: {}", err));
+            std::process::exit(1);
+        });
+        // Pages were already saved incrementally above.
+        reporter.success("Done.");
+        return;
     }
 
-    println!("\nDone.");
+    let pages: Vec<Page> = slots.into_iter().map(|p| p.unwrap()).collect();
+    reporter.success(&format!("Packaging {} pages as {}...", pages.len(), output_format));
+    archive::write_output(output_format, &cli.output, id, first, &pages, image_ext, &meta_ctx).unwrap_or_else(|err| {
+        reporter.error(&format!("while writing output: {}", err));
+        std::process::exit(1);
+    });
+
+    reporter.success("Done.");
 }
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Sidecar manifest recording the MD5 of the saved output bytes for every
+/// page that has already been downloaded and descrambled, so a later run
+/// can skip work that's still valid and only re-fetch what's missing or
+/// corrupted.
+///
+/// Deliberately hashes the *saved* bytes rather than the source JPEG: it
+/// catches local corruption of the file resume actually depends on, at the
+/// cost of not detecting the source changing upstream between runs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Page index -> MD5 hex digest of the bytes written for that page.
+    pages: HashMap<u32, String>,
+}
+
+impl Manifest {
+    /// Path of the manifest sidecar for a comic's output directory.
+    pub fn path_for(dir: &str) -> PathBuf {
+        Path::new(dir).join(".kirapo-manifest.json")
+    }
+
+    /// Loads the manifest at `path`, or an empty one if it doesn't exist or
+    /// fails to parse (treated the same as "nothing downloaded yet").
+    pub fn load(path: &Path) -> Manifest {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).unwrap();
+        std::fs::write(path, json)
+    }
+
+    /// Whether `page`'s output at `path` is still valid: recorded in a
+    /// previous run *and* its on-disk bytes still hash to what was
+    /// recorded, so a truncated/corrupted leftover from an interrupted run
+    /// is re-fetched instead of silently kept.
+    pub fn verify(&self, page: u32, path: &std::path::Path) -> bool {
+        match (self.pages.get(&page), std::fs::read(path)) {
+            (Some(expected), Ok(bytes)) => *expected == hash(&bytes),
+            _ => false,
+        }
+    }
+
+    pub fn record(&mut self, page: u32, hash: String) {
+        self.pages.insert(page, hash);
+    }
+}
+
+/// MD5 hex digest of `bytes`, used to detect truncated/corrupted downloads
+/// and to decide whether a cached page can be skipped on resume.
+pub fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", md5::compute(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(tag: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kirapo-manifest-test-{}-{}", tag, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn verify_accepts_a_page_whose_bytes_match_the_recorded_hash() {
+        let dir = temp_dir("match");
+        let path = dir.join("1.png");
+        std::fs::write(&path, b"page bytes").unwrap();
+
+        let mut manifest = Manifest::default();
+        manifest.record(1, hash(b"page bytes"));
+
+        assert!(manifest.verify(1, &path));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verify_rejects_a_page_whose_bytes_have_changed_since_recording() {
+        let dir = temp_dir("mismatch");
+        let path = dir.join("1.png");
+        std::fs::write(&path, b"truncated").unwrap();
+
+        let mut manifest = Manifest::default();
+        manifest.record(1, hash(b"page bytes"));
+
+        assert!(!manifest.verify(1, &path));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verify_rejects_a_page_never_recorded() {
+        let manifest = Manifest::default();
+        assert!(!manifest.verify(1, std::path::Path::new("/nonexistent/1.png")));
+    }
+}
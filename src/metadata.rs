@@ -0,0 +1,184 @@
+use image::RgbaImage;
+
+/// Where a descrambled page came from, embedded into the saved file itself
+/// so a copy that's later moved or renamed still carries its provenance.
+pub struct Provenance<'a> {
+    pub source_url: &'a str,
+    pub id: u32,
+    pub page_index: u32,
+    /// Dimensions of the scrambled tile sheet the page was assembled from.
+    pub scrambled_width: u32,
+    pub scrambled_height: u32,
+}
+
+impl<'a> Provenance<'a> {
+    fn description(&self) -> String {
+        format!(
+            "source={} id={} page={} scrambled={}x{}",
+            self.source_url, self.id, self.page_index, self.scrambled_width, self.scrambled_height
+        )
+    }
+}
+
+/// Encodes `image` as a PNG, embedding `provenance` as `tEXt` chunks
+/// (`Source` and `Comment`) when `Some`.
+pub fn png_bytes(image: &RgbaImage, provenance: Option<&Provenance>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, image.width(), image.height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        if let Some(p) = provenance {
+            encoder.add_text_chunk("Source".to_string(), p.source_url.to_string())?;
+            encoder.add_text_chunk("Comment".to_string(), p.description())?;
+        }
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(image.as_raw())?;
+    }
+    Ok(bytes)
+}
+
+/// Encodes `image` as a JPEG at `quality`, splicing an EXIF `APP1` segment
+/// carrying `ImageDescription`/`UserComment` right after the SOI marker
+/// when `provenance` is `Some`.
+pub fn jpeg_bytes(image: &RgbaImage, quality: u8, provenance: Option<&Provenance>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    image::DynamicImage::ImageRgba8(image.clone())
+        .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageOutputFormat::Jpeg(quality))?;
+
+    Ok(match provenance {
+        Some(p) => splice_app1(bytes, &exif_app1_segment(p)),
+        None => bytes,
+    })
+}
+
+/// Inserts an `APP1` segment right after the leading SOI marker (`FF D8`).
+fn splice_app1(jpeg: Vec<u8>, app1: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(jpeg.len() + app1.len());
+    out.extend_from_slice(&jpeg[..2]);
+    out.extend_from_slice(app1);
+    out.extend_from_slice(&jpeg[2..]);
+    out
+}
+
+/// Builds a minimal little-endian TIFF `APP1`/Exif segment with a single
+/// IFD0 holding `ImageDescription` (ASCII) and `UserComment` (ASCII-tagged
+/// UNDEFINED, as the EXIF spec requires).
+fn exif_app1_segment(p: &Provenance) -> Vec<u8> {
+    const TAG_IMAGE_DESCRIPTION: u16 = 0x010E;
+    const TAG_USER_COMMENT: u16 = 0x9286;
+    const TYPE_ASCII: u16 = 2;
+    const TYPE_UNDEFINED: u16 = 7;
+
+    let description = p.description();
+    let mut description_value = description.clone().into_bytes();
+    description_value.push(0); // NUL-terminated, per the ASCII type.
+
+    // UserComment carries the same fields as ImageDescription so either tag
+    // alone is enough to recover a page's provenance.
+    let mut comment_value = b"ASCII\0\0\0".to_vec(); // character-code designation
+    comment_value.extend_from_slice(description.as_bytes());
+
+    let entry_count: u16 = 2;
+    let ifd0_offset: u32 = 8;
+    let ifd_size = 2 + entry_count as u32 * 12 + 4;
+    let value_area_offset = ifd0_offset + ifd_size;
+    let description_offset = value_area_offset;
+    let comment_offset = description_offset + description_value.len() as u32;
+
+    let mut tiff = Vec::new();
+    tiff.extend_from_slice(b"II"); // little-endian
+    tiff.extend_from_slice(&42u16.to_le_bytes());
+    tiff.extend_from_slice(&ifd0_offset.to_le_bytes());
+
+    tiff.extend_from_slice(&entry_count.to_le_bytes());
+    write_ifd_entry(&mut tiff, TAG_IMAGE_DESCRIPTION, TYPE_ASCII, description_value.len() as u32, description_offset);
+    write_ifd_entry(&mut tiff, TAG_USER_COMMENT, TYPE_UNDEFINED, comment_value.len() as u32, comment_offset);
+    tiff.extend_from_slice(&0u32.to_le_bytes()); // no next IFD
+
+    tiff.extend_from_slice(&description_value);
+    tiff.extend_from_slice(&comment_value);
+
+    let mut segment = Vec::new();
+    segment.extend_from_slice(&[0xFF, 0xE1]);
+    let length = (2 + 6 + tiff.len()) as u16; // length field covers itself
+    segment.extend_from_slice(&length.to_be_bytes());
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(&tiff);
+    segment
+}
+
+fn write_ifd_entry(out: &mut Vec<u8>, tag: u16, field_type: u16, count: u32, value_or_offset: u32) {
+    out.extend_from_slice(&tag.to_le_bytes());
+    out.extend_from_slice(&field_type.to_le_bytes());
+    out.extend_from_slice(&count.to_le_bytes());
+    out.extend_from_slice(&value_or_offset.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_provenance() -> Provenance<'static> {
+        Provenance {
+            source_url: "https://kirapo.jp/123/viewer",
+            id: 123,
+            page_index: 4,
+            scrambled_width: 800,
+            scrambled_height: 1200,
+        }
+    }
+
+    /// Reads a single little-endian IFD0 entry back out of a TIFF-format
+    /// `exif_app1_segment` payload, returning its (type, count, value_or_offset).
+    fn read_ifd_entry(tiff: &[u8], index: usize) -> (u16, u32, u32) {
+        let ifd0_offset = u32::from_le_bytes(tiff[4..8].try_into().unwrap()) as usize;
+        let entry_offset = ifd0_offset + 2 + index * 12;
+        let field_type = u16::from_le_bytes(tiff[entry_offset + 2..entry_offset + 4].try_into().unwrap());
+        let count = u32::from_le_bytes(tiff[entry_offset + 4..entry_offset + 8].try_into().unwrap());
+        let value_or_offset = u32::from_le_bytes(tiff[entry_offset + 8..entry_offset + 12].try_into().unwrap());
+        (field_type, count, value_or_offset)
+    }
+
+    #[test]
+    fn exif_app1_segment_round_trips_image_description_and_user_comment() {
+        let p = sample_provenance();
+        let segment = exif_app1_segment(&p);
+
+        assert_eq!(&segment[0..2], &[0xFF, 0xE1]);
+        assert_eq!(&segment[4..10], b"Exif\0\0");
+        let length = u16::from_be_bytes([segment[2], segment[3]]) as usize;
+        assert_eq!(length, segment.len() - 2);
+
+        let tiff = &segment[10..];
+        assert_eq!(&tiff[0..2], b"II");
+        assert_eq!(u16::from_le_bytes([tiff[2], tiff[3]]), 42);
+
+        let (desc_type, desc_count, desc_offset) = read_ifd_entry(tiff, 0);
+        let (comment_type, comment_count, comment_offset) = read_ifd_entry(tiff, 1);
+
+        const TYPE_ASCII: u16 = 2;
+        const TYPE_UNDEFINED: u16 = 7;
+        assert_eq!(desc_type, TYPE_ASCII);
+        assert_eq!(comment_type, TYPE_UNDEFINED);
+
+        let description_bytes = &tiff[desc_offset as usize..(desc_offset + desc_count) as usize];
+        assert_eq!(description_bytes, format!("{}\0", p.description()).as_bytes());
+
+        let comment_bytes = &tiff[comment_offset as usize..(comment_offset + comment_count) as usize];
+        assert!(comment_bytes.starts_with(b"ASCII\0\0\0"));
+        assert_eq!(&comment_bytes[8..], p.description().as_bytes());
+    }
+
+    #[test]
+    fn splice_app1_inserts_the_segment_right_after_the_soi_marker() {
+        let jpeg = vec![0xFF, 0xD8, 0xFF, 0xD9]; // minimal SOI + EOI
+        let app1 = vec![0xFF, 0xE1, 0x00, 0x08, b'E', b'x', b'i', b'f'];
+
+        let spliced = splice_app1(jpeg.clone(), &app1);
+
+        assert_eq!(&spliced[0..2], &jpeg[0..2]);
+        assert_eq!(&spliced[2..2 + app1.len()], &app1[..]);
+        assert_eq!(&spliced[2 + app1.len()..], &jpeg[2..]);
+    }
+}
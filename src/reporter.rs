@@ -0,0 +1,71 @@
+use colored::Colorize;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+/// Centralizes progress and status output so the download/descramble/save
+/// loops don't scatter raw `print!`/`eprintln!` calls. Bars are suppressed
+/// entirely in `--quiet` mode; `--verbose` additionally logs every fetched URL.
+pub struct Reporter {
+    quiet: bool,
+    verbose: bool,
+    multi: MultiProgress,
+}
+
+impl Reporter {
+    pub fn new(quiet: bool, verbose: bool) -> Reporter {
+        Reporter {
+            quiet,
+            verbose,
+            multi: MultiProgress::new(),
+        }
+    }
+
+    /// A spinner for a phase with no known total up front (page discovery).
+    pub fn spinner(&self, message: &str) -> Option<ProgressBar> {
+        if self.quiet {
+            return None;
+        }
+        let pb = self.multi.add(ProgressBar::new_spinner());
+        pb.set_style(ProgressStyle::with_template("{spinner:.cyan} {msg}").unwrap());
+        pb.set_message(message.to_string());
+        pb.enable_steady_tick(std::time::Duration::from_millis(100));
+        Some(pb)
+    }
+
+    /// A counted progress bar for a phase with a known total (download, save).
+    pub fn bar(&self, message: &str, total: u64) -> Option<ProgressBar> {
+        if self.quiet {
+            return None;
+        }
+        let pb = self.multi.add(ProgressBar::new(total));
+        pb.set_style(
+            ProgressStyle::with_template(
+                "{msg} [{bar:30.cyan/blue}] {pos}/{len} ({per_sec}, eta {eta})",
+            )
+            .unwrap()
+            .progress_chars("=> "),
+        );
+        pb.set_message(message.to_string());
+        Some(pb)
+    }
+
+    /// Logs a fetched URL; a no-op unless `--verbose` was passed.
+    pub fn log_url(&self, url: &str) {
+        if self.verbose {
+            println!("{} {}", "GET".blue().bold(), url);
+        }
+    }
+
+    pub fn success(&self, message: &str) {
+        if !self.quiet {
+            println!("{}", message.green());
+        }
+    }
+
+    pub fn warning(&self, message: &str) {
+        eprintln!("{} {}", "warning:".yellow().bold(), message);
+    }
+
+    pub fn error(&self, message: &str) {
+        eprintln!("{} {}", "error:".red().bold(), message);
+    }
+}